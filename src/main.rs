@@ -1,6 +1,6 @@
 #![feature(async_closure)]
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use egg_mode::cursor::{CursorIter, UserCursor};
 use egg_mode::user::{self, TwitterUser};
 use egg_mode::{self, Token};
@@ -8,17 +8,71 @@ use futures::future;
 use miette::{self, Diagnostic};
 use rusqlite::{named_params, Connection};
 use serde::Deserialize;
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::Write as _;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tracing::{event, info_span, warn_span, Level};
 
 const PAGE_SIZE: usize = 200;
-const ME: &str = "djanatyn";
+/// Extra buffer added on top of the rate limit reset time, so we don't wake
+/// up right at the boundary and get rate limited again immediately.
+const RATE_LIMIT_JITTER_SECONDS: i64 = 5;
 
 #[derive(Deserialize, Debug)]
 struct Config {
     fetch_followers_token: String,
+    /// Comma-separated list of screen names to snapshot, e.g. "djanatyn,foo".
+    profiles: String,
+    /// How many consecutive rate-limit waits to ride out before giving up
+    /// and failing the session.
+    #[serde(default = "default_max_rate_limit_retries")]
+    max_rate_limit_retries: usize,
+    /// Minutes a cached snapshot hash is considered fresh before we
+    /// re-snapshot a user even if their metadata hasn't changed.
+    #[serde(default = "default_refetch_interval_minutes")]
+    refetch_interval_minutes: i64,
+    /// Maximum number of users to keep in the in-memory snapshot cache.
+    #[serde(default = "default_snapshot_cache_size")]
+    snapshot_cache_size: usize,
+    /// If set, export snapshots as newline-delimited JSON to this path
+    /// instead of writing to the SQLite database.
+    #[serde(default)]
+    export_path: Option<String>,
+}
+
+fn default_max_rate_limit_retries() -> usize {
+    5
+}
+
+fn default_refetch_interval_minutes() -> i64 {
+    30
+}
+
+fn default_snapshot_cache_size() -> usize {
+    50_000
+}
+
+/// Parse the comma-separated `profiles` config value into screen names.
+fn parse_profiles(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Derive a stable pseudo profile ID for export-mode sessions, where there's
+/// no database to assign a real one from.
+fn profile_id_from_screen_name(screen_name: &str) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    screen_name.hash(&mut hasher);
+    (hasher.finish() >> 1) as i64
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -44,17 +98,51 @@ enum Error {
     #[error("unexpected error inserting into DB: {0:#?}")]
     FailedInsert(rusqlite::Error),
 
+    #[error("failed to diff sessions: {0:#?}")]
+    FailedDiff(rusqlite::Error),
+
+    #[error("failed to write export file: {0:#?}")]
+    FailedExport(std::io::Error),
+
+    #[error("failed to serialize export record: {0:#?}")]
+    FailedSerialize(serde_json::Error),
+
     #[error("unknown error")]
     Unknown,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum UserType {
     Followers,
     Following,
 }
 
-#[derive(Debug)]
+impl UserType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UserType::Followers => "followers",
+            UserType::Following => "following",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Whether a user was gained or lost between two sessions.
+enum ChangeKind {
+    Gained,
+    Lost,
+}
+
+impl ChangeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Gained => "GAINED",
+            ChangeKind::Lost => "LOST",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// A snapshot of a user's metadata taken during a session.
 struct UserSnapshot {
     /// User ID (from Twitter, not the database)
@@ -81,6 +169,89 @@ struct UserSnapshot {
     verified: bool,
 }
 
+/// Hash the metadata fields of a snapshot that change over time, for
+/// detecting whether a user's profile has actually moved since we last
+/// saw them.
+fn hash_snapshot(snap: &UserSnapshot) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    snap.screen_name.hash(&mut hasher);
+    snap.location.hash(&mut hasher);
+    snap.description.hash(&mut hasher);
+    snap.url.hash(&mut hasher);
+    snap.follower_count.hash(&mut hasher);
+    snap.following_count.hash(&mut hasher);
+    snap.status_count.hash(&mut hasher);
+    snap.verified.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A cached snapshot hash and when we last saw it, for TTL-based dedup.
+struct CacheEntry {
+    hash: u64,
+    last_seen: DateTime<Utc>,
+}
+
+/// In-memory, size-bounded cache of recently-seen snapshot hashes.
+///
+/// Lets `flip_pages` skip re-inserting identical profile metadata for users
+/// who haven't changed since the last time we saw them within
+/// `refetch_interval`.
+#[derive(Debug)]
+struct SnapshotCache {
+    entries: HashMap<u64, CacheEntry>,
+    max_size: usize,
+    refetch_interval: Duration,
+}
+
+impl SnapshotCache {
+    fn new(max_size: usize, refetch_interval: Duration) -> Self {
+        SnapshotCache {
+            entries: HashMap::new(),
+            max_size,
+            refetch_interval,
+        }
+    }
+
+    /// Whether `user_id`'s current metadata hash matches a cached entry
+    /// that's still within the refetch interval.
+    fn is_fresh(&self, user_id: u64, hash: u64, now: DateTime<Utc>) -> bool {
+        match self.entries.get(&user_id) {
+            Some(entry) => entry.hash == hash && now - entry.last_seen < self.refetch_interval,
+            None => false,
+        }
+    }
+
+    /// Record that we've just seen `user_id` with this metadata hash,
+    /// evicting the oldest entry first if the cache is full.
+    fn record(&mut self, user_id: u64, hash: u64, now: DateTime<Utc>) {
+        if self.entries.len() >= self.max_size && !self.entries.contains_key(&user_id) {
+            self.evict_oldest();
+        }
+
+        self.entries.insert(user_id, CacheEntry { hash, last_seen: now });
+    }
+
+    /// Evict the single least-recently-seen entry.
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_seen)
+            .map(|(user_id, _)| *user_id);
+
+        if let Some(user_id) = oldest {
+            self.entries.remove(&user_id);
+        }
+    }
+
+    /// Evict entries older than the refetch interval.
+    fn evict_expired(&mut self, now: DateTime<Utc>) {
+        self.entries
+            .retain(|_, entry| now - entry.last_seen < self.refetch_interval);
+    }
+}
+
 #[derive(Debug)]
 /// Commands to send to DB worker.
 enum DatabaseCommand {
@@ -90,11 +261,21 @@ enum DatabaseCommand {
     StoreFollower(u64),
     /// Store a user ID as someone we are following.
     StoreFollowing(u64),
+    /// Record the in-flight cursor for a walk, so a restart can resume it.
+    StoreProgress {
+        user_type: UserType,
+        next_cursor: i64,
+    },
+    /// Flush any buffered writes to disk in a single transaction.
+    Flush,
     /// Mark a session as failed.
     FailedSession,
 }
 
-/// Run init.sql, a non-destructive script to create tables.
+/// Run init.sql, a non-destructive script to create tables, then apply any
+/// migrations needed to bring an existing database up to the current
+/// schema (init.sql's `CREATE TABLE IF NOT EXISTS` is a no-op against
+/// tables that already exist, so it can never add a column to one).
 fn init_db<P: AsRef<Path>>(path: P) -> miette::Result<Connection> {
     warn_span!("init_db").in_scope(|| {
         let db: Connection = match Connection::open(path) {
@@ -105,24 +286,105 @@ fn init_db<P: AsRef<Path>>(path: P) -> miette::Result<Connection> {
             }
         };
 
-        match db.execute(include_str!("init.sql"), []) {
+        match db.execute_batch(include_str!("init.sql")) {
             Err(e) => Err(Error::FailedInitialization(e))?,
-            Ok(updated) => {
-                event!(Level::WARN, updated, "ran init script");
-                Ok(db)
-            }
+            Ok(()) => event!(Level::WARN, "ran init script"),
         }
+
+        migrate_profile_id_columns(&db)?;
+
+        Ok(db)
     })
 }
 
-/// Initialize a session, recording the current start time.
+/// Whether `table` already has a column named `column`.
+fn column_exists(db: &Connection, table: &str, column: &str) -> miette::Result<bool> {
+    let mut stmt = db
+        .prepare(&format!("PRAGMA table_info({table})"))
+        .expect("failed to prepare statement");
+
+    let mut rows = stmt.query([]).map_err(Error::FailedInitialization)?;
+    while let Some(row) = rows.next().map_err(Error::FailedInitialization)? {
+        let name: String = row.get(1).map_err(Error::FailedInitialization)?;
+        if name == column {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Migrate a database created before profiles existed (chunk0-3) by adding
+/// `profile_id` columns to the tables that now require them.
+///
+/// Existing rows are backfilled onto a sentinel `__unknown__` profile via
+/// `ALTER TABLE ... ADD COLUMN ... DEFAULT`, so old snapshots aren't lost or
+/// left with a dangling foreign key. Each table's column is checked and
+/// added independently (rather than gating the whole migration on
+/// `sessions` alone) and all four `ALTER TABLE`s run in one transaction, so
+/// a process killed partway through leaves nothing half-migrated: either
+/// the migration didn't run at all, or it's fully applied.
+fn migrate_profile_id_columns(db: &Connection) -> miette::Result<()> {
+    let tables = ["sessions", "snapshots", "followers", "following"];
+    if tables
+        .iter()
+        .all(|table| column_exists(db, table, "profile_id").unwrap_or(false))
+    {
+        return Ok(());
+    }
+
+    event!(Level::WARN, "migrating database: adding profile_id columns");
+
+    let sentinel_profile_id = get_or_create_profile(db, "__unknown__")?;
+    let tx = db.unchecked_transaction().map_err(Error::FailedInitialization)?;
+
+    for table in tables {
+        if column_exists(&tx, table, "profile_id")? {
+            continue;
+        }
+
+        tx.execute(
+            &format!(
+                "ALTER TABLE {table} ADD COLUMN profile_id INTEGER NOT NULL \
+                 REFERENCES profiles (id) DEFAULT {sentinel_profile_id}"
+            ),
+            [],
+        )
+        .map_err(Error::FailedInitialization)?;
+    }
+
+    tx.commit().map_err(Error::FailedInitialization)?;
+
+    Ok(())
+}
+
+/// Look up a profile by screen name, inserting it if it doesn't exist yet.
+///
+/// Returns the profile's ID within the database.
+fn get_or_create_profile(db: &Connection, screen_name: &str) -> miette::Result<i64> {
+    db.execute(
+        "INSERT OR IGNORE INTO profiles (screen_name) VALUES (:screen_name)",
+        named_params! { ":screen_name": screen_name },
+    )
+    .map_err(Error::FailedInsert)?;
+
+    db.query_row(
+        "SELECT id FROM profiles WHERE screen_name = :screen_name",
+        named_params! { ":screen_name": screen_name },
+        |row| row.get(0),
+    )
+    .map_err(Error::FailedInsert)
+}
+
+/// Initialize a session for a profile, recording the current start time.
 ///
 /// Returns ID of Session within the database.
-fn init_session(db: &Connection) -> miette::Result<i64> {
+fn init_session(db: &Connection, profile_id: i64) -> miette::Result<i64> {
     let now = Utc::now();
     let rows = db.execute(
-        "INSERT INTO sessions (start_time) VALUES (:start)",
+        "INSERT INTO sessions (profile_id, start_time) VALUES (:profile_id, :start)",
         named_params! {
+            ":profile_id": profile_id,
             ":start": now.timestamp()
         },
     );
@@ -142,50 +404,61 @@ fn init_session(db: &Connection) -> miette::Result<i64> {
 ///
 /// We may get the same user as both a follower and following. In that case,
 /// "INSERT OR IGNORE" will respect the unique constraints.
-fn write_snapshot(session_id: i32, db: &Connection, snap: &UserSnapshot) -> miette::Result<usize> {
-    let result = db.execute(
-        "INSERT OR IGNORE INTO snapshots (
-            user_id,
-            session_id,
-            snapshot_time,
-            created_date,
-            screen_name,
-            location,
-            description,
-            url,
-            follower_count,
-            following_count,
-            status_count,
-            verified
-        ) VALUES (
-            :user_id,
-            :session_id,
-            :snapshot_time,
-            :created_date,
-            :screen_name,
-            :location,
-            :description,
-            :url,
-            :follower_count,
-            :following_count,
-            :status_count,
-            :verified
-        )",
-        named_params! {
-            ":user_id": snap.user_id,
-            ":session_id": session_id,
-            ":snapshot_time": snap.snapshot_time.timestamp(),
-            ":created_date": snap.created_date.timestamp(),
-            ":screen_name": snap.screen_name,
-            ":location": snap.location,
-            ":description": snap.description,
-            ":url": snap.url,
-            ":follower_count": snap.follower_count,
-            ":following_count": snap.following_count,
-            ":status_count": snap.status_count,
-            ":verified": snap.verified
-        },
-    );
+fn write_snapshot(
+    session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+    snap: &UserSnapshot,
+) -> miette::Result<usize> {
+    let mut stmt = db
+        .prepare_cached(
+            "INSERT OR IGNORE INTO snapshots (
+                user_id,
+                profile_id,
+                session_id,
+                snapshot_time,
+                created_date,
+                screen_name,
+                location,
+                description,
+                url,
+                follower_count,
+                following_count,
+                status_count,
+                verified
+            ) VALUES (
+                :user_id,
+                :profile_id,
+                :session_id,
+                :snapshot_time,
+                :created_date,
+                :screen_name,
+                :location,
+                :description,
+                :url,
+                :follower_count,
+                :following_count,
+                :status_count,
+                :verified
+            )",
+        )
+        .expect("failed to prepare statement");
+
+    let result = stmt.execute(named_params! {
+        ":user_id": snap.user_id,
+        ":profile_id": profile_id,
+        ":session_id": session_id,
+        ":snapshot_time": snap.snapshot_time.timestamp(),
+        ":created_date": snap.created_date.timestamp(),
+        ":screen_name": snap.screen_name,
+        ":location": snap.location,
+        ":description": snap.description,
+        ":url": snap.url,
+        ":follower_count": snap.follower_count,
+        ":following_count": snap.following_count,
+        ":status_count": snap.status_count,
+        ":verified": snap.verified
+    });
 
     match result {
         Ok(updated) => {
@@ -196,6 +469,50 @@ fn write_snapshot(session_id: i32, db: &Connection, snap: &UserSnapshot) -> miet
     }
 }
 
+/// Rehydrate a SnapshotCache from the latest snapshot recorded per user for
+/// a profile, so the dedup cache survives a process restart.
+fn rehydrate_snapshot_cache(db: &Connection, profile_id: i64, cache: &mut SnapshotCache) -> miette::Result<()> {
+    let mut stmt = db
+        .prepare(
+            "SELECT user_id, screen_name, location, description, url,
+                    follower_count, following_count, status_count, verified,
+                    MAX(snapshot_time) AS snapshot_time
+             FROM snapshots
+             WHERE profile_id = :profile_id
+             GROUP BY user_id",
+        )
+        .expect("failed to prepare statement");
+
+    let rows = stmt
+        .query_map(named_params! { ":profile_id": profile_id }, |row| {
+            let user_id: u64 = row.get(0)?;
+            let snap = UserSnapshot {
+                user_id,
+                snapshot_time: Utc::now(),
+                created_date: Utc::now(),
+                screen_name: row.get(1)?,
+                location: row.get(2)?,
+                description: row.get(3)?,
+                url: row.get(4)?,
+                follower_count: row.get(5)?,
+                following_count: row.get(6)?,
+                status_count: row.get(7)?,
+                verified: row.get(8)?,
+            };
+            let snapshot_time: i64 = row.get(9)?;
+            Ok((user_id, hash_snapshot(&snap), snapshot_time))
+        })
+        .map_err(Error::FailedDiff)?;
+
+    for row in rows {
+        let (user_id, hash, snapshot_time) = row.map_err(Error::FailedDiff)?;
+        let last_seen = DateTime::from_timestamp(snapshot_time, 0).unwrap_or_else(Utc::now);
+        cache.record(user_id, hash, last_seen);
+    }
+
+    Ok(())
+}
+
 /// Try to load Twitter API Bearer token from environment variables.
 fn load_config() -> miette::Result<Config> {
     match envy::from_env::<Config>() {
@@ -204,27 +521,97 @@ fn load_config() -> miette::Result<Config> {
     }
 }
 
+/// Park until a rate limit resets, plus a small jitter buffer.
+async fn wait_for_rate_limit(reset: i32, retries: usize) {
+    let now = Utc::now().timestamp();
+    let wait = (reset as i64 - now).max(0) + RATE_LIMIT_JITTER_SECONDS;
+
+    warn_span!("rate_limit", reset, wait, retries).in_scope(|| {
+        event!(Level::WARN, wait, retries, "hit rate limit, parking until reset");
+    });
+
+    tokio::time::sleep(std::time::Duration::from_secs(wait as u64)).await;
+}
+
+/// Call `attempt` again while it keeps returning a rate limit error,
+/// parking between each try, up to `max_rate_limit_retries` consecutive
+/// waits. Shared between the first-page and next-page retry loops in
+/// `flip_pages`.
+///
+/// On exhaustion, sends `FailedSession` and returns `Error::RateLimit`
+/// instead of calling `attempt` again.
+async fn ride_out_rate_limit<T, F, Fut>(
+    tx: &Sender<DatabaseCommand>,
+    max_rate_limit_retries: usize,
+    rate_limit_retries: &mut usize,
+    mut attempt: F,
+    first: Result<T, egg_mode::error::Error>,
+) -> miette::Result<Result<T, egg_mode::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, egg_mode::error::Error>>,
+{
+    let mut result = first;
+
+    while let Err(egg_mode::error::Error::RateLimit(reset)) = result {
+        if *rate_limit_retries >= max_rate_limit_retries {
+            tx.send(DatabaseCommand::FailedSession)
+                .await
+                .expect("send error");
+            Err(Error::RateLimit(reset))?
+        }
+
+        wait_for_rate_limit(reset, *rate_limit_retries).await;
+        *rate_limit_retries += 1;
+        result = attempt().await;
+    }
+
+    Ok(result)
+}
+
 /// Flip through paginated results of users.
 /// Used with `user::followers_of` and `user::friends_of`.
+///
+/// Rate limits don't fail the session: we park until the reset timestamp
+/// and resume from the same cursor, up to `max_rate_limit_retries`
+/// consecutive waits before giving up.
+///
+/// If `resume_cursor` is set (from a previous run's persisted
+/// `session_progress` row), the walk starts there instead of from the
+/// first page.
 async fn flip_pages(
     tx: Sender<DatabaseCommand>,
     mut pages: CursorIter<UserCursor>,
     user_type: UserType,
+    max_rate_limit_retries: usize,
+    cache: Arc<Mutex<SnapshotCache>>,
+    resume_cursor: Option<i64>,
 ) -> miette::Result<Vec<TwitterUser>> {
     // initialize user list
     let mut users: Vec<TwitterUser> = Vec::new();
+    let mut rate_limit_retries = 0;
 
-    // check for rate limit on first call
-    let mut cursor = pages.call().await;
-    if let Err(egg_mode::error::Error::RateLimit(timestamp)) = cursor {
-        tx.send(DatabaseCommand::FailedSession)
-            .await
-            .expect("send error");
-        Err(Error::RateLimit(timestamp))?
+    if let Some(cursor) = resume_cursor {
+        event!(Level::WARN, cursor, ?user_type, "resuming walk from persisted cursor");
+        pages.next_cursor = cursor;
     }
 
+    // check for rate limit on first call, parking and retrying as needed
+    let first = pages.call().await;
+    let mut cursor = ride_out_rate_limit(
+        &tx,
+        max_rate_limit_retries,
+        &mut rate_limit_retries,
+        || pages.call(),
+        first,
+    )
+    .await?;
+
     // loop over successful, non-empty responses
     while let Ok(ref mut response) = cursor {
+        // a successful page resets the rate limit retry count
+        rate_limit_retries = 0;
+
         // stop if there are no users in the response
         if response.users.is_empty() {
             break;
@@ -234,11 +621,24 @@ async fn flip_pages(
         event!(Level::WARN, length, "fetched page");
 
         for user in &response.users {
-            // write the user snapshot
+            // skip re-snapshotting users whose metadata hasn't changed
+            // since the last time we saw them, within the refetch interval
             let snapshot = user_snapshot(user);
-            tx.send(DatabaseCommand::StoreSnapshot(snapshot))
-                .await
-                .expect("send error");
+            let hash = hash_snapshot(&snapshot);
+            let now = Utc::now();
+
+            let fresh = {
+                let mut cache = cache.lock().expect("cache poisoned");
+                let fresh = cache.is_fresh(snapshot.user_id, hash, now);
+                cache.record(snapshot.user_id, hash, now);
+                fresh
+            };
+
+            if !fresh {
+                tx.send(DatabaseCommand::StoreSnapshot(snapshot))
+                    .await
+                    .expect("send error");
+            }
 
             // after that, record the user as follower / following
             let msg = match user_type {
@@ -254,16 +654,34 @@ async fn flip_pages(
 
         // get next page
         pages.next_cursor = response.next_cursor;
-        cursor = pages.call().await;
 
-        // check for errors before continuing
+        // persist the in-flight cursor so a restart can resume this walk
+        tx.send(DatabaseCommand::StoreProgress {
+            user_type,
+            next_cursor: pages.next_cursor,
+        })
+        .await
+        .expect("send error");
+
+        // flush the batch of writes from this page to disk
+        tx.send(DatabaseCommand::Flush).await.expect("send error");
+
+        // sweep TTL-expired entries out of the dedup cache
+        cache.lock().expect("cache poisoned").evict_expired(Utc::now());
+
+        // ride out rate limits on the next page too, retrying in place
+        let next = pages.call().await;
+        cursor = ride_out_rate_limit(
+            &tx,
+            max_rate_limit_retries,
+            &mut rate_limit_retries,
+            || pages.call(),
+            next,
+        )
+        .await?;
+
+        // check for non-rate-limit errors before continuing
         match cursor {
-            Err(egg_mode::error::Error::RateLimit(timestamp)) => {
-                tx.send(DatabaseCommand::FailedSession)
-                    .await
-                    .expect("send error");
-                Err(Error::RateLimit(timestamp))?
-            }
             Err(_) => {
                 tx.send(DatabaseCommand::FailedSession)
                     .await
@@ -278,41 +696,73 @@ async fn flip_pages(
     Ok(users)
 }
 
-/// Fetch my followers.
+/// Fetch a profile's followers.
 async fn fetch_followers(
     tx: Sender<DatabaseCommand>,
     token: &Token,
+    screen_name: &str,
+    max_rate_limit_retries: usize,
+    cache: Arc<Mutex<SnapshotCache>>,
+    resume_cursor: Option<i64>,
 ) -> miette::Result<Vec<TwitterUser>> {
-    let span = warn_span!("fetch_followers");
+    let span = warn_span!("fetch_followers", screen_name);
     span.in_scope(async || {
-        let followers = user::followers_of(ME, token).with_page_size(PAGE_SIZE as i32);
-        flip_pages(tx, followers, UserType::Followers).await
+        let followers = user::followers_of(screen_name, token).with_page_size(PAGE_SIZE as i32);
+        flip_pages(
+            tx,
+            followers,
+            UserType::Followers,
+            max_rate_limit_retries,
+            cache,
+            resume_cursor,
+        )
+        .await
     })
     .await
 }
 
-/// Fetch users I am following.
+/// Fetch users a profile is following.
 async fn fetch_following(
     tx: Sender<DatabaseCommand>,
     token: &Token,
+    screen_name: &str,
+    max_rate_limit_retries: usize,
+    cache: Arc<Mutex<SnapshotCache>>,
+    resume_cursor: Option<i64>,
 ) -> miette::Result<Vec<TwitterUser>> {
-    let span = warn_span!("fetch_following");
+    let span = warn_span!("fetch_following", screen_name);
     span.in_scope(async || {
-        let following = user::friends_of(ME, token).with_page_size(PAGE_SIZE as i32);
-        flip_pages(tx, following, UserType::Following).await
+        let following = user::friends_of(screen_name, token).with_page_size(PAGE_SIZE as i32);
+        flip_pages(
+            tx,
+            following,
+            UserType::Following,
+            max_rate_limit_retries,
+            cache,
+            resume_cursor,
+        )
+        .await
     })
     .await
 }
 
 /// Record a user as a follower.
-fn store_follower(session_id: i32, db: &Connection, user_id: u64) -> miette::Result<usize> {
-    let rows = db.execute(
-        "INSERT INTO followers (user_id, session_id) VALUES (:user_id, :session_id)",
-        named_params! {
-            ":user_id": user_id,
-            ":session_id": session_id,
-        },
-    );
+fn store_follower(
+    session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+    user_id: u64,
+) -> miette::Result<usize> {
+    let mut stmt = db
+        .prepare_cached(
+            "INSERT INTO followers (user_id, profile_id, session_id) VALUES (:user_id, :profile_id, :session_id)",
+        )
+        .expect("failed to prepare statement");
+    let rows = stmt.execute(named_params! {
+        ":user_id": user_id,
+        ":profile_id": profile_id,
+        ":session_id": session_id,
+    });
 
     let updated = match rows {
         Err(e) => Err(Error::FailedInsert(e))?,
@@ -326,24 +776,309 @@ fn store_follower(session_id: i32, db: &Connection, user_id: u64) -> miette::Res
 }
 
 /// Record a user as someone you're following.
-fn store_following(session_id: i32, db: &Connection, user_id: u64) -> miette::Result<usize> {
+fn store_following(
+    session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+    user_id: u64,
+) -> miette::Result<usize> {
+    let mut stmt = db
+        .prepare_cached(
+            "INSERT INTO following (user_id, profile_id, session_id) VALUES (:user_id, :profile_id, :session_id)",
+        )
+        .expect("failed to prepare statement");
+    let rows = stmt.execute(named_params! {
+        ":user_id": user_id,
+        ":profile_id": profile_id,
+        ":session_id": session_id,
+    });
+
+    let updated = match rows {
+        Err(e) => Err(Error::FailedInsert(e))?,
+        Ok(updated) => {
+            event!(Level::WARN, user_id, "wrote following");
+            updated
+        }
+    };
+
+    Ok(updated)
+}
+
+/// Record the in-flight cursor for a walk, so a process restart can resume
+/// mid-walk instead of starting over.
+fn store_progress(
+    session_id: i32,
+    db: &Connection,
+    user_type: UserType,
+    next_cursor: i64,
+) -> miette::Result<usize> {
+    let now = Utc::now();
+    let mut stmt = db
+        .prepare_cached(
+            "INSERT OR REPLACE INTO session_progress (
+                session_id, user_type, next_cursor, updated_time
+            ) VALUES (:session_id, :user_type, :next_cursor, :updated_time)",
+        )
+        .expect("failed to prepare statement");
+    let rows = stmt.execute(named_params! {
+        ":session_id": session_id,
+        ":user_type": user_type.as_str(),
+        ":next_cursor": next_cursor,
+        ":updated_time": now.timestamp(),
+    });
+
+    match rows {
+        Err(e) => Err(Error::FailedInsert(e))?,
+        Ok(updated) => {
+            event!(Level::WARN, ?user_type, next_cursor, "wrote progress");
+            Ok(updated)
+        }
+    }
+}
+
+/// Load the last persisted cursor for a walk within a session, if any.
+fn load_progress(db: &Connection, session_id: i32, user_type: UserType) -> miette::Result<Option<i64>> {
+    let result = db.query_row(
+        "SELECT next_cursor FROM session_progress
+         WHERE session_id = :session_id AND user_type = :user_type",
+        named_params! { ":session_id": session_id, ":user_type": user_type.as_str() },
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(cursor) => Ok(Some(cursor)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::FailedInsert(e))?,
+    }
+}
+
+/// Find a still-RUNNING session for a profile, if a previous run was
+/// interrupted before finishing (or failing) it.
+fn find_running_session(db: &Connection, profile_id: i64) -> miette::Result<Option<i64>> {
+    let result = db.query_row(
+        "SELECT id FROM sessions
+         WHERE profile_id = :profile_id AND session_state = 'RUNNING'
+         ORDER BY id DESC LIMIT 1",
+        named_params! { ":profile_id": profile_id },
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::FailedDiff(e))?,
+    }
+}
+
+/// Find the most recent FINISHED session for a profile before the given
+/// session, if any.
+///
+/// Never matches a FAILED session, so a diff never compares against a run
+/// that didn't complete.
+fn previous_finished_session(
+    session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+) -> miette::Result<Option<i32>> {
+    let result = db.query_row(
+        "SELECT id FROM sessions
+         WHERE session_state = 'FINISHED' AND profile_id = :profile_id AND id < :id
+         ORDER BY id DESC LIMIT 1",
+        named_params! { ":id": session_id, ":profile_id": profile_id },
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(Error::FailedDiff(e))?,
+    }
+}
+
+/// Load the set of follower user IDs recorded for a session.
+fn load_followers(session_id: i32, db: &Connection) -> miette::Result<HashSet<u64>> {
+    let mut stmt = db
+        .prepare("SELECT user_id FROM followers WHERE session_id = :session_id")
+        .expect("failed to prepare statement");
+
+    let rows = stmt
+        .query_map(named_params! { ":session_id": session_id }, |row| {
+            row.get(0)
+        })
+        .map_err(Error::FailedDiff)?;
+
+    let mut ids = HashSet::new();
+    for row in rows {
+        ids.insert(row.map_err(Error::FailedDiff)?);
+    }
+
+    Ok(ids)
+}
+
+/// Load the set of following user IDs recorded for a session.
+fn load_following(session_id: i32, db: &Connection) -> miette::Result<HashSet<u64>> {
+    let mut stmt = db
+        .prepare("SELECT user_id FROM following WHERE session_id = :session_id")
+        .expect("failed to prepare statement");
+
+    let rows = stmt
+        .query_map(named_params! { ":session_id": session_id }, |row| {
+            row.get(0)
+        })
+        .map_err(Error::FailedDiff)?;
+
+    let mut ids = HashSet::new();
+    for row in rows {
+        ids.insert(row.map_err(Error::FailedDiff)?);
+    }
+
+    Ok(ids)
+}
+
+/// Record a single follower delta between two sessions.
+fn store_follower_change(
+    db: &Connection,
+    user_id: u64,
+    change: ChangeKind,
+    previous_session_id: Option<i32>,
+    current_session_id: i32,
+) -> miette::Result<usize> {
+    let now = Utc::now();
     let rows = db.execute(
-        "INSERT INTO following (user_id, session_id) VALUES (:user_id, :session_id)",
+        "INSERT INTO follower_changes (
+            user_id, change_type, previous_session_id, current_session_id, changed_time
+        ) VALUES (
+            :user_id, :change_type, :previous_session_id, :current_session_id, :changed_time
+        )",
         named_params! {
             ":user_id": user_id,
-            ":session_id": session_id,
+            ":change_type": change.as_str(),
+            ":previous_session_id": previous_session_id,
+            ":current_session_id": current_session_id,
+            ":changed_time": now.timestamp(),
         },
     );
 
-    let updated = match rows {
+    match rows {
         Err(e) => Err(Error::FailedInsert(e))?,
         Ok(updated) => {
-            event!(Level::WARN, user_id, "wrote following");
-            updated
+            event!(Level::WARN, user_id, change = change.as_str(), "wrote follower change");
+            Ok(updated)
+        }
+    }
+}
+
+/// Record a single following delta between two sessions.
+fn store_following_change(
+    db: &Connection,
+    user_id: u64,
+    change: ChangeKind,
+    previous_session_id: Option<i32>,
+    current_session_id: i32,
+) -> miette::Result<usize> {
+    let now = Utc::now();
+    let rows = db.execute(
+        "INSERT INTO following_changes (
+            user_id, change_type, previous_session_id, current_session_id, changed_time
+        ) VALUES (
+            :user_id, :change_type, :previous_session_id, :current_session_id, :changed_time
+        )",
+        named_params! {
+            ":user_id": user_id,
+            ":change_type": change.as_str(),
+            ":previous_session_id": previous_session_id,
+            ":current_session_id": current_session_id,
+            ":changed_time": now.timestamp(),
+        },
+    );
+
+    match rows {
+        Err(e) => Err(Error::FailedInsert(e))?,
+        Ok(updated) => {
+            event!(Level::WARN, user_id, change = change.as_str(), "wrote following change");
+            Ok(updated)
         }
+    }
+}
+
+/// Diff this session's followers against the most recent prior FINISHED
+/// session, materializing GAINED/LOST rows into `follower_changes`.
+///
+/// If there is no prior FINISHED session, everyone in this session is
+/// recorded as GAINED.
+fn compute_follower_changes(
+    current_session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+) -> miette::Result<()> {
+    let previous_session_id = previous_finished_session(current_session_id, profile_id, db)?;
+    let current = load_followers(current_session_id, db)?;
+    let previous = match previous_session_id {
+        Some(id) => load_followers(id, db)?,
+        None => HashSet::new(),
     };
 
-    Ok(updated)
+    for &user_id in current.difference(&previous) {
+        store_follower_change(
+            db,
+            user_id,
+            ChangeKind::Gained,
+            previous_session_id,
+            current_session_id,
+        )?;
+    }
+
+    for &user_id in previous.difference(&current) {
+        store_follower_change(
+            db,
+            user_id,
+            ChangeKind::Lost,
+            previous_session_id,
+            current_session_id,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Diff this session's following against the most recent prior FINISHED
+/// session, materializing GAINED/LOST rows into `following_changes`.
+///
+/// If there is no prior FINISHED session, everyone in this session is
+/// recorded as GAINED.
+fn compute_following_changes(
+    current_session_id: i32,
+    profile_id: i64,
+    db: &Connection,
+) -> miette::Result<()> {
+    let previous_session_id = previous_finished_session(current_session_id, profile_id, db)?;
+    let current = load_following(current_session_id, db)?;
+    let previous = match previous_session_id {
+        Some(id) => load_following(id, db)?,
+        None => HashSet::new(),
+    };
+
+    for &user_id in current.difference(&previous) {
+        store_following_change(
+            db,
+            user_id,
+            ChangeKind::Gained,
+            previous_session_id,
+            current_session_id,
+        )?;
+    }
+
+    for &user_id in previous.difference(&current) {
+        store_following_change(
+            db,
+            user_id,
+            ChangeKind::Lost,
+            previous_session_id,
+            current_session_id,
+        )?;
+    }
+
+    Ok(())
 }
 
 /// Mark session as failed, recording the time.
@@ -403,32 +1138,429 @@ fn user_snapshot(user: &TwitterUser) -> UserSnapshot {
     }
 }
 
+/// A storage backend for a snapshot session.
+///
+/// `db_manager` is generic over this trait, so the SQLite-backed store
+/// (`SqliteStore`) is one implementation among others: tests can plug in an
+/// in-memory fake, and `ExportStore` gives us a newline-delimited JSON
+/// backend for free without touching the command interpreter.
+trait SnapshotStore {
+    /// Start a session for a profile, returning its ID.
+    fn init_session(&mut self, profile_id: i64) -> miette::Result<i64>;
+    /// Record a user snapshot.
+    fn write_snapshot(&mut self, session_id: i32, profile_id: i64, snap: UserSnapshot) -> miette::Result<()>;
+    /// Record a user as a follower.
+    fn store_follower(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()>;
+    /// Record a user as someone we're following.
+    fn store_following(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()>;
+    /// Record the in-flight cursor for a walk.
+    fn store_progress(&mut self, session_id: i32, user_type: UserType, next_cursor: i64) -> miette::Result<()>;
+    /// Load the last persisted cursor for a walk within a session, so an
+    /// interrupted walk can resume instead of starting over. Backends with
+    /// no queryable store (e.g. `ExportStore`) can always return `None`.
+    fn load_progress(&self, session_id: i32, user_type: UserType) -> miette::Result<Option<i64>>;
+    /// Flush any buffered writes.
+    fn flush(&mut self) -> miette::Result<()>;
+    /// Mark a session as finished.
+    fn finalize_session(&mut self, session_id: i32) -> miette::Result<()>;
+    /// Mark a session as failed.
+    fn fail_session(&mut self, session_id: i32) -> miette::Result<()>;
+}
+
+/// A single buffered write, queued by `SqliteStore` until the next flush.
+enum PendingWrite {
+    Snapshot {
+        session_id: i32,
+        profile_id: i64,
+        snap: UserSnapshot,
+    },
+    Follower {
+        session_id: i32,
+        profile_id: i64,
+        user_id: u64,
+    },
+    Following {
+        session_id: i32,
+        profile_id: i64,
+        user_id: u64,
+    },
+    Progress {
+        session_id: i32,
+        user_type: UserType,
+        next_cursor: i64,
+    },
+}
+
+/// SQLite-backed `SnapshotStore`.
+///
+/// Buffers writes and flushes them together in one transaction, rather than
+/// autocommitting one row at a time. Statements are prepared with
+/// `prepare_cached`, so they're reused across flushes instead of being
+/// re-prepared per row.
+struct SqliteStore<'a> {
+    db: &'a Connection,
+    pending: Vec<PendingWrite>,
+}
+
+impl<'a> SqliteStore<'a> {
+    fn new(db: &'a Connection) -> Self {
+        SqliteStore {
+            db,
+            pending: Vec::with_capacity(PAGE_SIZE),
+        }
+    }
+}
+
+impl<'a> SnapshotStore for SqliteStore<'a> {
+    fn init_session(&mut self, profile_id: i64) -> miette::Result<i64> {
+        // resume a previous run's session instead of starting a fresh one,
+        // if it was interrupted before it could be finalized or failed
+        if let Some(session_id) = find_running_session(self.db, profile_id)? {
+            event!(Level::WARN, session_id, "resuming interrupted session");
+            return Ok(session_id);
+        }
+
+        init_session(self.db, profile_id)
+    }
+
+    fn write_snapshot(&mut self, session_id: i32, profile_id: i64, snap: UserSnapshot) -> miette::Result<()> {
+        self.pending.push(PendingWrite::Snapshot {
+            session_id,
+            profile_id,
+            snap,
+        });
+        Ok(())
+    }
+
+    fn store_follower(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()> {
+        self.pending.push(PendingWrite::Follower {
+            session_id,
+            profile_id,
+            user_id,
+        });
+        Ok(())
+    }
+
+    fn store_following(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()> {
+        self.pending.push(PendingWrite::Following {
+            session_id,
+            profile_id,
+            user_id,
+        });
+        Ok(())
+    }
+
+    fn store_progress(&mut self, session_id: i32, user_type: UserType, next_cursor: i64) -> miette::Result<()> {
+        self.pending.push(PendingWrite::Progress {
+            session_id,
+            user_type,
+            next_cursor,
+        });
+        Ok(())
+    }
+
+    fn load_progress(&self, session_id: i32, user_type: UserType) -> miette::Result<Option<i64>> {
+        load_progress(self.db, session_id, user_type)
+    }
+
+    fn flush(&mut self) -> miette::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.db.unchecked_transaction().map_err(Error::FailedInsert)?;
+
+        for write in self.pending.drain(..) {
+            match write {
+                PendingWrite::Snapshot {
+                    session_id,
+                    profile_id,
+                    snap,
+                } => {
+                    write_snapshot(session_id, profile_id, &tx, &snap)?;
+                }
+                PendingWrite::Follower {
+                    session_id,
+                    profile_id,
+                    user_id,
+                } => {
+                    store_follower(session_id, profile_id, &tx, user_id)?;
+                }
+                PendingWrite::Following {
+                    session_id,
+                    profile_id,
+                    user_id,
+                } => {
+                    store_following(session_id, profile_id, &tx, user_id)?;
+                }
+                PendingWrite::Progress {
+                    session_id,
+                    user_type,
+                    next_cursor,
+                } => {
+                    store_progress(session_id, &tx, user_type, next_cursor)?;
+                }
+            }
+        }
+
+        tx.commit().map_err(Error::FailedInsert)?;
+
+        Ok(())
+    }
+
+    fn finalize_session(&mut self, session_id: i32) -> miette::Result<()> {
+        finalize_session(session_id, self.db)?;
+        Ok(())
+    }
+
+    fn fail_session(&mut self, session_id: i32) -> miette::Result<()> {
+        fail_session(session_id, self.db)?;
+        Ok(())
+    }
+}
+
+/// Newline-delimited JSON `SnapshotStore`, for exporting a session without a
+/// queryable backing store.
+///
+/// Each record is a `serde_json::Value` built by hand rather than a derived
+/// `Serialize` impl on `UserSnapshot`, so we aren't tied to chrono's serde
+/// feature flag for `DateTime<Utc>` fields.
+struct ExportStore<W: std::io::Write> {
+    writer: W,
+    /// Shared across every profile in a run, so session IDs stay unique
+    /// within that run's output instead of restarting at 1 per profile. The
+    /// counter starts at 0 each run, so a later run appending to the same
+    /// file reuses the same IDs.
+    next_session_id: Arc<Mutex<i32>>,
+}
+
+impl<W: std::io::Write> ExportStore<W> {
+    fn new(writer: W, next_session_id: Arc<Mutex<i32>>) -> Self {
+        ExportStore {
+            writer,
+            next_session_id,
+        }
+    }
+
+    fn write_record(&mut self, record: serde_json::Value) -> miette::Result<()> {
+        let line = serde_json::to_string(&record).map_err(Error::FailedSerialize)?;
+        writeln!(self.writer, "{line}").map_err(Error::FailedExport)?;
+        Ok(())
+    }
+}
+
+impl<W: std::io::Write> SnapshotStore for ExportStore<W> {
+    fn init_session(&mut self, profile_id: i64) -> miette::Result<i64> {
+        let session_id = {
+            let mut next_session_id = self.next_session_id.lock().expect("session id counter poisoned");
+            *next_session_id += 1;
+            *next_session_id
+        };
+
+        self.write_record(json!({
+            "type": "session_started",
+            "session_id": session_id,
+            "profile_id": profile_id,
+            "start_time": Utc::now().timestamp(),
+        }))?;
+
+        Ok(session_id as i64)
+    }
+
+    fn write_snapshot(&mut self, session_id: i32, profile_id: i64, snap: UserSnapshot) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "snapshot",
+            "session_id": session_id,
+            "profile_id": profile_id,
+            "user_id": snap.user_id,
+            "snapshot_time": snap.snapshot_time.timestamp(),
+            "created_date": snap.created_date.timestamp(),
+            "screen_name": snap.screen_name,
+            "location": snap.location,
+            "description": snap.description,
+            "url": snap.url,
+            "follower_count": snap.follower_count,
+            "following_count": snap.following_count,
+            "status_count": snap.status_count,
+            "verified": snap.verified,
+        }))
+    }
+
+    fn store_follower(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "follower",
+            "session_id": session_id,
+            "profile_id": profile_id,
+            "user_id": user_id,
+        }))
+    }
+
+    fn store_following(&mut self, session_id: i32, profile_id: i64, user_id: u64) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "following",
+            "session_id": session_id,
+            "profile_id": profile_id,
+            "user_id": user_id,
+        }))
+    }
+
+    fn store_progress(&mut self, session_id: i32, user_type: UserType, next_cursor: i64) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "progress",
+            "session_id": session_id,
+            "user_type": user_type.as_str(),
+            "next_cursor": next_cursor,
+        }))
+    }
+
+    fn load_progress(&self, _session_id: i32, _user_type: UserType) -> miette::Result<Option<i64>> {
+        // an appended-to NDJSON file isn't a queryable store, so an export
+        // session can never resume a prior run's walk
+        Ok(None)
+    }
+
+    fn flush(&mut self) -> miette::Result<()> {
+        self.writer.flush().map_err(Error::FailedExport)?;
+        Ok(())
+    }
+
+    fn finalize_session(&mut self, session_id: i32) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "session_finished",
+            "session_id": session_id,
+            "finish_time": Utc::now().timestamp(),
+        }))
+    }
+
+    fn fail_session(&mut self, session_id: i32) -> miette::Result<()> {
+        self.write_record(json!({
+            "type": "session_failed",
+            "session_id": session_id,
+            "finish_time": Utc::now().timestamp(),
+        }))
+    }
+}
+
 /// Interpreter task for DatabaseCommand channel.
-async fn db_manager(
+///
+/// Delegates each command to the storage backend, which decides how (and
+/// whether) to batch writes before they land; `Flush` and the channel
+/// draining both request an explicit flush, and every `PAGE_SIZE` commands
+/// triggers one too, instead of autocommitting one row at a time.
+async fn db_manager<S: SnapshotStore>(
     session_id: i32,
-    db: &Connection,
+    profile_id: i64,
+    store: &mut S,
     rx: &mut Receiver<DatabaseCommand>,
 ) -> miette::Result<()> {
+    let mut pending = 0usize;
+
     while let Some(cmd) = rx.recv().await {
         match cmd {
-            DatabaseCommand::StoreSnapshot(snapshot) => {
-                write_snapshot(session_id, db, &snapshot)?;
+            DatabaseCommand::StoreSnapshot(snap) => {
+                store.write_snapshot(session_id, profile_id, snap)?;
+                pending += 1;
             }
             DatabaseCommand::StoreFollower(user_id) => {
-                store_follower(session_id, db, user_id)?;
+                store.store_follower(session_id, profile_id, user_id)?;
+                pending += 1;
             }
             DatabaseCommand::StoreFollowing(user_id) => {
-                store_following(session_id, db, user_id)?;
+                store.store_following(session_id, profile_id, user_id)?;
+                pending += 1;
+            }
+            DatabaseCommand::StoreProgress {
+                user_type,
+                next_cursor,
+            } => {
+                store.store_progress(session_id, user_type, next_cursor)?;
+                pending += 1;
+            }
+            DatabaseCommand::Flush => {
+                store.flush()?;
+                pending = 0;
             }
             DatabaseCommand::FailedSession => {
-                fail_session(session_id, db)?;
+                // don't lose buffered rows just because the session failed
+                store.flush()?;
+                store.fail_session(session_id)?;
+                pending = 0;
             }
         }
+
+        if pending >= PAGE_SIZE {
+            store.flush()?;
+            pending = 0;
+        }
     }
 
+    // the channel closed: flush whatever's left before finalizing
+    store.flush()?;
+
     Ok(())
 }
 
+/// Run a full snapshot session for a single profile against a storage
+/// backend, returning the session ID it was assigned.
+///
+/// Diffing against the previous session (`compute_follower_changes` /
+/// `compute_following_changes`) is left to the caller, since it requires a
+/// queryable backing store and isn't something every `SnapshotStore` impl
+/// can support.
+async fn run_profile_session<S: SnapshotStore>(
+    store: &mut S,
+    profile_id: i64,
+    token: &Token,
+    screen_name: &str,
+    max_rate_limit_retries: usize,
+    cache: Arc<Mutex<SnapshotCache>>,
+) -> miette::Result<i32> {
+    let session = store.init_session(profile_id)?;
+
+    // pick up where a previous, interrupted run left off, if it got that far
+    let following_cursor = store.load_progress(session as i32, UserType::Following)?;
+    let followers_cursor = store.load_progress(session as i32, UserType::Followers)?;
+
+    // create channel for DatabaseCommand
+    let (tx1, mut rx) = mpsc::channel::<DatabaseCommand>(32);
+    let tx2 = tx1.clone();
+
+    // retrieve followers + following
+    let (following, followers, _) = future::try_join3(
+        fetch_following(
+            tx1,
+            token,
+            screen_name,
+            max_rate_limit_retries,
+            Arc::clone(&cache),
+            following_cursor,
+        ),
+        fetch_followers(
+            tx2,
+            token,
+            screen_name,
+            max_rate_limit_retries,
+            cache,
+            followers_cursor,
+        ),
+        db_manager(session as i32, profile_id, store, &mut rx),
+    )
+    .await?;
+
+    let follower_count = followers.len();
+    let following_count = following.len();
+    event!(
+        Level::WARN,
+        follower_count,
+        following_count,
+        "finished session, finalizing"
+    );
+
+    store.finalize_session(session as i32)?;
+
+    Ok(session as i32)
+}
+
 #[tokio::main]
 async fn main() -> miette::Result<()> {
     // load config, setup tracing
@@ -437,36 +1569,406 @@ async fn main() -> miette::Result<()> {
     let span = info_span!("session");
     span.in_scope(async || {
         // construct bearer token for twitter API
+        let max_rate_limit_retries = config.max_rate_limit_retries;
+        let refetch_interval = Duration::minutes(config.refetch_interval_minutes);
+        let snapshot_cache_size = config.snapshot_cache_size;
+        let profiles = parse_profiles(&config.profiles);
         let token = Token::Bearer(config.fetch_followers_token);
 
         let db = init_db("followers.sqlite")?;
-        let session = init_session(&db)?;
 
-        // create channel for DatabaseCommand
-        let (tx1, mut rx) = mpsc::channel::<DatabaseCommand>(32);
-        let tx2 = tx1.clone();
+        // shared across every profile below, so exported session IDs stay
+        // unique within this run instead of restarting at 1 for each profile
+        let next_export_session_id = Arc::new(Mutex::new(0));
 
-        // retrieve followers + following
-        let (following, followers, _) = future::try_join3(
-            fetch_following(tx1, &token),
-            fetch_followers(tx2, &token),
-            db_manager(session as i32, &db, &mut rx),
-        )
-        .await?;
+        // snapshot each configured profile as its own logical sub-session
+        for screen_name in &profiles {
+            let profile_span = warn_span!("profile", screen_name);
+            let next_export_session_id = Arc::clone(&next_export_session_id);
+            let result = profile_span
+                .in_scope(async || match &config.export_path {
+                    None => {
+                        let profile_id = get_or_create_profile(&db, screen_name)?;
 
-        let follower_count = followers.len();
-        let following_count = following.len();
-        event!(
-            Level::WARN,
-            follower_count,
-            following_count,
-            "finished session, finalizing"
-        );
+                        // rehydrate the dedup cache from the latest snapshot
+                        // per user, so a restart doesn't immediately
+                        // re-snapshot everyone
+                        let mut cache = SnapshotCache::new(snapshot_cache_size, refetch_interval);
+                        rehydrate_snapshot_cache(&db, profile_id, &mut cache)?;
+                        let cache = Arc::new(Mutex::new(cache));
+
+                        let mut store = SqliteStore::new(&db);
+                        let session_id = run_profile_session(
+                            &mut store,
+                            profile_id,
+                            &token,
+                            screen_name,
+                            max_rate_limit_retries,
+                            cache,
+                        )
+                        .await?;
+
+                        compute_follower_changes(session_id, profile_id, &db)?;
+                        compute_following_changes(session_id, profile_id, &db)?;
+
+                        Ok(())
+                    }
+                    Some(export_path) => {
+                        // no database backing this session, so there's no
+                        // real profile ID to look up, and no prior session
+                        // to diff against
+                        let profile_id = profile_id_from_screen_name(screen_name);
+                        let cache = Arc::new(Mutex::new(SnapshotCache::new(
+                            snapshot_cache_size,
+                            refetch_interval,
+                        )));
+
+                        let file = std::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(export_path)
+                            .map_err(Error::FailedExport)?;
+                        let mut store = ExportStore::new(file, next_export_session_id);
+
+                        run_profile_session(
+                            &mut store,
+                            profile_id,
+                            &token,
+                            screen_name,
+                            max_rate_limit_retries,
+                            cache,
+                        )
+                        .await?;
+
+                        Ok(())
+                    }
+                })
+                .await;
+
+            // a single profile failing (e.g. exhausting its rate limit
+            // retries) shouldn't take the rest of the batch down with it
+            if let Err(error) = result {
+                event!(Level::ERROR, screen_name, ?error, "profile session failed, skipping");
+            }
+        }
 
-        finalize_session(session as i32, &db)?;
         event!(Level::WARN, "complete :)");
 
         Ok(())
     })
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_user_snapshot(user_id: u64) -> UserSnapshot {
+        let now = Utc::now();
+        UserSnapshot {
+            user_id,
+            snapshot_time: now,
+            created_date: now,
+            screen_name: format!("user_{user_id}"),
+            location: None,
+            description: None,
+            url: None,
+            follower_count: 0,
+            following_count: 0,
+            status_count: 0,
+            verified: false,
+        }
+    }
+
+    fn test_db() -> Connection {
+        let db = Connection::open_in_memory().expect("failed to open in-memory db");
+        db.execute_batch(include_str!("init.sql")).expect("failed to run init.sql");
+        db
+    }
+
+    /// An in-memory fake `SnapshotStore`, so the command interpreter (and
+    /// anything that drives it) can be tested without a real SQLite file.
+    #[derive(Debug, Default)]
+    struct FakeStore {
+        next_session_id: i32,
+        snapshots: Vec<UserSnapshot>,
+        followers: HashSet<u64>,
+        following: HashSet<u64>,
+        progress: HashMap<&'static str, i64>,
+        flushed: usize,
+        finalized: Vec<i32>,
+        failed: Vec<i32>,
+    }
+
+    impl SnapshotStore for FakeStore {
+        fn init_session(&mut self, _profile_id: i64) -> miette::Result<i64> {
+            self.next_session_id += 1;
+            Ok(self.next_session_id as i64)
+        }
+
+        fn write_snapshot(&mut self, _session_id: i32, _profile_id: i64, snap: UserSnapshot) -> miette::Result<()> {
+            self.snapshots.push(snap);
+            Ok(())
+        }
+
+        fn store_follower(&mut self, _session_id: i32, _profile_id: i64, user_id: u64) -> miette::Result<()> {
+            self.followers.insert(user_id);
+            Ok(())
+        }
+
+        fn store_following(&mut self, _session_id: i32, _profile_id: i64, user_id: u64) -> miette::Result<()> {
+            self.following.insert(user_id);
+            Ok(())
+        }
+
+        fn store_progress(&mut self, _session_id: i32, user_type: UserType, next_cursor: i64) -> miette::Result<()> {
+            self.progress.insert(user_type.as_str(), next_cursor);
+            Ok(())
+        }
+
+        fn load_progress(&self, _session_id: i32, user_type: UserType) -> miette::Result<Option<i64>> {
+            Ok(self.progress.get(user_type.as_str()).copied())
+        }
+
+        fn flush(&mut self) -> miette::Result<()> {
+            self.flushed += 1;
+            Ok(())
+        }
+
+        fn finalize_session(&mut self, session_id: i32) -> miette::Result<()> {
+            self.finalized.push(session_id);
+            Ok(())
+        }
+
+        fn fail_session(&mut self, session_id: i32) -> miette::Result<()> {
+            self.failed.push(session_id);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn db_manager_routes_commands_to_the_store_and_flushes_on_drain() {
+        let mut store = FakeStore::default();
+        let (tx, mut rx) = mpsc::channel::<DatabaseCommand>(8);
+
+        tx.send(DatabaseCommand::StoreSnapshot(test_user_snapshot(1)))
+            .await
+            .unwrap();
+        tx.send(DatabaseCommand::StoreFollower(1)).await.unwrap();
+        tx.send(DatabaseCommand::StoreProgress {
+            user_type: UserType::Followers,
+            next_cursor: 42,
+        })
+        .await
+        .unwrap();
+        drop(tx);
+
+        db_manager(1, 1, &mut store, &mut rx).await.unwrap();
+
+        assert_eq!(store.snapshots.len(), 1);
+        assert!(store.followers.contains(&1));
+        assert_eq!(store.progress.get("followers"), Some(&42));
+        assert_eq!(store.flushed, 1);
+    }
+
+    #[tokio::test]
+    async fn db_manager_flushes_then_fails_session_on_failed_session() {
+        let mut store = FakeStore::default();
+        let (tx, mut rx) = mpsc::channel::<DatabaseCommand>(8);
+
+        tx.send(DatabaseCommand::StoreFollower(7)).await.unwrap();
+        tx.send(DatabaseCommand::FailedSession).await.unwrap();
+        drop(tx);
+
+        db_manager(3, 1, &mut store, &mut rx).await.unwrap();
+
+        assert!(store.followers.contains(&7));
+        assert_eq!(store.failed, vec![3]);
+    }
+
+    #[test]
+    fn compute_follower_changes_marks_everyone_gained_on_first_session() {
+        let db = test_db();
+        let profile_id = get_or_create_profile(&db, "alice").unwrap();
+        let session_id = init_session(&db, profile_id).unwrap() as i32;
+
+        store_follower(session_id, profile_id, &db, 1).unwrap();
+        store_follower(session_id, profile_id, &db, 2).unwrap();
+        finalize_session(session_id, &db).unwrap();
+
+        compute_follower_changes(session_id, profile_id, &db).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT user_id, change_type, previous_session_id FROM follower_changes ORDER BY user_id")
+            .unwrap();
+        let rows: Vec<(u64, String, Option<i32>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![(1, "GAINED".to_string(), None), (2, "GAINED".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn compute_follower_changes_never_diffs_against_a_failed_session() {
+        let db = test_db();
+        let profile_id = get_or_create_profile(&db, "bob").unwrap();
+
+        // a failed session with followers that should never be diffed against
+        let failed_session_id = init_session(&db, profile_id).unwrap() as i32;
+        store_follower(failed_session_id, profile_id, &db, 99).unwrap();
+        fail_session(failed_session_id, &db).unwrap();
+
+        let session_id = init_session(&db, profile_id).unwrap() as i32;
+        store_follower(session_id, profile_id, &db, 1).unwrap();
+        finalize_session(session_id, &db).unwrap();
+
+        compute_follower_changes(session_id, profile_id, &db).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT user_id, change_type, previous_session_id FROM follower_changes")
+            .unwrap();
+        let rows: Vec<(u64, String, Option<i32>)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        // user 1 is GAINED against no prior FINISHED session; user 99 from
+        // the FAILED session is never considered
+        assert_eq!(rows, vec![(1, "GAINED".to_string(), None)]);
+    }
+
+    #[test]
+    fn snapshot_cache_evicts_expired_entries() {
+        let mut cache = SnapshotCache::new(10, Duration::minutes(30));
+        let now = Utc::now();
+        let expired = now - Duration::minutes(31);
+
+        cache.record(1, 111, expired);
+        cache.record(2, 222, now);
+
+        cache.evict_expired(now);
+
+        assert!(!cache.is_fresh(1, 111, now));
+        assert!(cache.is_fresh(2, 222, now));
+    }
+
+    #[test]
+    fn snapshot_cache_evicts_oldest_entry_when_full() {
+        let mut cache = SnapshotCache::new(2, Duration::minutes(30));
+        let now = Utc::now();
+
+        cache.record(1, 1, now - Duration::minutes(2));
+        cache.record(2, 2, now - Duration::minutes(1));
+        cache.record(3, 3, now);
+
+        assert!(!cache.is_fresh(1, 1, now));
+        assert!(cache.is_fresh(2, 2, now));
+        assert!(cache.is_fresh(3, 3, now));
+    }
+
+    #[test]
+    fn export_store_writes_one_json_record_per_line() {
+        let mut store = ExportStore::new(Vec::<u8>::new(), Arc::new(Mutex::new(0)));
+
+        let session_id = store.init_session(42).unwrap() as i32;
+        store.write_snapshot(session_id, 42, test_user_snapshot(7)).unwrap();
+        store.store_follower(session_id, 42, 7).unwrap();
+        store.finalize_session(session_id).unwrap();
+
+        let output = String::from_utf8(store.writer).unwrap();
+        let records: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["type"], "session_started");
+        assert_eq!(records[0]["session_id"], 1);
+        assert_eq!(records[1]["type"], "snapshot");
+        assert_eq!(records[1]["user_id"], 7);
+        assert_eq!(records[2]["type"], "follower");
+        assert_eq!(records[3]["type"], "session_finished");
+    }
+
+    #[tokio::test]
+    async fn ride_out_rate_limit_gives_up_after_max_retries_and_fails_session() {
+        let (tx, mut rx) = mpsc::channel::<DatabaseCommand>(8);
+        let mut rate_limit_retries = 0;
+
+        let result = ride_out_rate_limit(
+            &tx,
+            0,
+            &mut rate_limit_retries,
+            || std::future::ready(Err::<(), _>(egg_mode::error::Error::RateLimit(0))),
+            Err(egg_mode::error::Error::RateLimit(0)),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(matches!(rx.recv().await, Some(DatabaseCommand::FailedSession)));
+    }
+
+    #[test]
+    fn migrate_profile_id_columns_adds_columns_to_a_pre_profile_schema() {
+        let db = Connection::open_in_memory().expect("failed to open in-memory db");
+        db.execute_batch(
+            "CREATE TABLE sessions (
+                id INTEGER PRIMARY KEY,
+                start_time INTEGER NOT NULL,
+                finish_time INTEGER,
+                session_state TEXT NOT NULL DEFAULT 'RUNNING'
+            );
+            CREATE TABLE snapshots (
+                user_id INTEGER NOT NULL,
+                session_id INTEGER NOT NULL REFERENCES sessions (id),
+                snapshot_time INTEGER NOT NULL,
+                created_date INTEGER NOT NULL,
+                screen_name TEXT NOT NULL,
+                location TEXT,
+                description TEXT,
+                url TEXT,
+                follower_count INTEGER NOT NULL,
+                following_count INTEGER NOT NULL,
+                status_count INTEGER NOT NULL,
+                verified BOOLEAN NOT NULL,
+                UNIQUE (user_id, session_id)
+            );
+            CREATE TABLE followers (
+                user_id INTEGER NOT NULL,
+                session_id INTEGER NOT NULL REFERENCES sessions (id),
+                UNIQUE (user_id, session_id)
+            );
+            CREATE TABLE following (
+                user_id INTEGER NOT NULL,
+                session_id INTEGER NOT NULL REFERENCES sessions (id),
+                UNIQUE (user_id, session_id)
+            );",
+        )
+        .expect("failed to create pre-migration schema");
+
+        // init_db's init.sql adds the `profiles` table; `CREATE TABLE IF NOT
+        // EXISTS` is a no-op for the other four, which already exist here
+        // without a profile_id column.
+        db.execute_batch(include_str!("init.sql")).expect("failed to run init.sql");
+
+        migrate_profile_id_columns(&db).expect("migration failed");
+
+        for table in ["sessions", "snapshots", "followers", "following"] {
+            assert!(column_exists(&db, table, "profile_id").unwrap(), "{table} missing profile_id");
+        }
+    }
+
+    #[test]
+    fn migrate_profile_id_columns_is_a_no_op_on_an_already_migrated_schema() {
+        let db = test_db();
+
+        migrate_profile_id_columns(&db).expect("migration should be a no-op");
+
+        for table in ["sessions", "snapshots", "followers", "following"] {
+            assert!(column_exists(&db, table, "profile_id").unwrap());
+        }
+    }
+}